@@ -0,0 +1,890 @@
+//! Settings of a server and of its listeners, loaded from a TOML, YAML or JSON file and from
+//! `SAPHIR_*` environment variables, letting a deployment be tuned without recompiling.
+//!
+//! Every setting is optional, an unset setting keeps the default of the
+//! [`ListenerBuilder`](../server/struct.ListenerBuilder.html). Durations are in milliseconds and a
+//! timeout or a limit set to 0 is disabled.
+//!
+//! ```toml
+//! shutdown_timeout_ms = 10000
+//!
+//! [[listeners]]
+//! interface = "0.0.0.0:443"
+//! request_timeout_ms = 15000
+//! max_connections = 10000
+//! connection_limit_behavior = "reject"
+//!
+//! [listeners.tls]
+//! cert_path = "/etc/app/cert.pem"
+//! key_path = "/etc/app/key.pem"
+//! min_version = "1.3"
+//!
+//! [middlewares.log]
+//! prefix = "API"
+//! ```
+//!
+//! Environment variables override the file, they are named after the settings in upper case:
+//!
+//! | Variable                                | Setting                                      |
+//! |-----------------------------------------|----------------------------------------------|
+//! | `SAPHIR_SHUTDOWN_TIMEOUT_MS`            | `shutdown_timeout_ms`                        |
+//! | `SAPHIR_LISTENER_REQUEST_TIMEOUT_MS`    | `request_timeout_ms` of the first listener   |
+//! | `SAPHIR_LISTENER_1_INTERFACE`           | `interface` of the second listener           |
+//! | `SAPHIR_LISTENER_TLS_CERT_PATH`         | `tls.cert_path` of the first listener        |
+//! | `SAPHIR_LISTENER_PROXY_PROTOCOL`        | `proxy_protocol`, as a comma separated list  |
+//! | `SAPHIR_MIDDLEWARE_LOG__PREFIX`         | `prefix` of the `log` middleware settings    |
+//!
+//! Listeners referenced by the environment are added when the file does not describe them, and
+//! every listener needs an `interface` or a `unix_socket`. A `SAPHIR_*` variable which does not
+//! name a known setting is an error. The unix socket permissions are read in octal from the
+//! environment, e.g. `660`.
+//!
+//! ```rust,no_run
+//!# use saphir::prelude::*;
+//! use saphir::config::ServerSettings;
+//!
+//!# async fn log_middleware(prefix: &String, ctx: HttpContext<Body>, chain: &dyn MiddlewareChain) -> Result<Response<Body>, SaphirError> { chain.next(ctx).await }
+//!# async fn run() -> Result<(), SaphirError> {
+//! let settings = ServerSettings::load("/etc/app/saphir.toml")?;
+//! let prefix = settings.middleware::<LogSettings>("log")?.map(|s| s.prefix).unwrap_or_default();
+//!
+//! let server = Server::builder()
+//!     .with_settings(&settings)?
+//!     .configure_middlewares(|m| m.apply(log_middleware, prefix, vec!["/"], None))
+//!     .build();
+//!
+//! server.run().await
+//!# }
+//!
+//! #[derive(serde::Deserialize)]
+//! struct LogSettings {
+//!     prefix: String,
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use http::StatusCode;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::Deserialize;
+
+use crate::error::ConfigError;
+use crate::server::{ConnectionLimitBehavior, HttpProtocol, ListenerBuilder, DEFAULT_HTTP2_KEEP_ALIVE_TIMEOUT_MS, MIN_HTTP1_MAX_BUF_SIZE, UPGRADE_ENV_PREFIX};
+#[cfg(feature = "https")]
+use crate::server::{ClientAuth, SslConfig};
+
+/// Prefix of the environment variables read by
+/// [`ServerSettings::apply_env`](struct.ServerSettings.html#method.apply_env)
+pub const ENV_PREFIX: &str = "SAPHIR_";
+
+/// Format of a configuration file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// TOML, `.toml` files
+    Toml,
+    /// YAML, `.yaml` and `.yml` files
+    Yaml,
+    /// JSON, `.json` files
+    Json,
+}
+
+impl ConfigFormat {
+    /// Tell the format of a file from its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "json" => Some(ConfigFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Settings of a server, its listeners and its middlewares
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// See [`Builder::shutdown_timeout`](../server/struct.Builder.html#method.shutdown_timeout)
+    pub shutdown_timeout_ms: Option<u64>,
+    /// See [`Builder::panic_status`](../server/struct.Builder.html#method.panic_status)
+    pub panic_status: Option<u16>,
+    /// Listeners of the server
+    pub listeners: Vec<ListenerSettings>,
+    /// Free form settings of the middlewares, by middleware name, see
+    /// [`middleware`](#method.middleware)
+    pub middlewares: HashMap<String, serde_json::Value>,
+}
+
+impl ServerSettings {
+    /// Load the settings from a file, its format being told by its extension, then apply the
+    /// `SAPHIR_*` environment variables and validate the result
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let mut settings = Self::from_file(path)?;
+        settings.apply_env()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Load the settings from the `SAPHIR_*` environment variables only and validate them
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let mut settings = Self::default();
+        settings.apply_env()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Read the settings from a file, its format being told by its extension
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let source = path.display().to_string();
+        let format = ConfigFormat::from_path(path).ok_or_else(|| ConfigError::UnknownFormat(source.clone()))?;
+        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::File(source.clone(), e))?;
+        Self::parse_source(&content, format, &source)
+    }
+
+    /// Read the settings from the content of a configuration file
+    pub fn parse(content: &str, format: ConfigFormat) -> Result<Self, ConfigError> {
+        Self::parse_source(content, format, "configuration")
+    }
+
+    fn parse_source(content: &str, format: ConfigFormat, source: &str) -> Result<Self, ConfigError> {
+        let settings = match format {
+            ConfigFormat::Toml => toml::from_str(content).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+        };
+
+        settings.map_err(|msg| ConfigError::Parse(source.to_string(), msg))
+    }
+
+    /// Override the settings with the `SAPHIR_*` environment variables, see the
+    /// [module documentation](index.html)
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        self.apply_env_vars(std::env::vars())
+    }
+
+    /// Override the settings with the given `SAPHIR_*` variables, other variables are ignored
+    ///
+    /// ```rust
+    /// use saphir::config::{ConfigFormat, ServerSettings};
+    ///
+    /// let mut settings = ServerSettings::parse(r#"
+    ///     [[listeners]]
+    ///     interface = "0.0.0.0:80"
+    ///     request_timeout_ms = 15000
+    /// "#, ConfigFormat::Toml).unwrap();
+    ///
+    /// settings.apply_env_vars(vec![
+    ///     ("SAPHIR_LISTENER_REQUEST_TIMEOUT_MS".to_string(), "5000".to_string()),
+    ///     ("SAPHIR_LISTENER_1_INTERFACE".to_string(), "127.0.0.1:8080".to_string()),
+    ///     ("SAPHIR_LISTENER_1_REQUEST_TIMEOUT_STATUS".to_string(), "1000".to_string()),
+    /// ]).unwrap();
+    /// assert_eq!(settings.listeners[0].request_timeout_ms, Some(5000));
+    ///
+    /// let error = settings.validate().unwrap_err();
+    /// assert_eq!(error.to_string(), "invalid setting listeners[1].request_timeout_status: 1000 is not a valid http status");
+    /// ```
+    pub fn apply_env_vars<I: IntoIterator<Item = (String, String)>>(&mut self, vars: I) -> Result<(), ConfigError> {
+        // The variables passed to a process started by a server upgrade are not settings
+        let mut vars = EnvVars(vars.into_iter().filter(|(key, _)| key.starts_with(ENV_PREFIX) && !key.starts_with(UPGRADE_ENV_PREFIX)).collect());
+
+        vars.take("SAPHIR_SHUTDOWN_TIMEOUT_MS", &mut self.shutdown_timeout_ms)?;
+        vars.take("SAPHIR_PANIC_STATUS", &mut self.panic_status)?;
+
+        // The first listener can be referenced with or without its index
+        let listener_count = vars.0.keys()
+            .filter_map(|key| key.strip_prefix("SAPHIR_LISTENER_"))
+            .map(|rest| match rest.split('_').next().and_then(|index| index.parse::<usize>().ok()) {
+                Some(index) => index + 1,
+                None => 1,
+            })
+            .max()
+            .unwrap_or(0);
+        let described = self.listeners.len();
+        if described < listener_count {
+            self.listeners.resize_with(listener_count, ListenerSettings::default);
+        }
+
+        let mut referenced = described;
+        for (index, listener) in self.listeners.iter_mut().enumerate() {
+            let left = vars.0.len();
+            if index == 0 {
+                listener.apply_env(&mut vars, "SAPHIR_LISTENER_")?;
+            }
+            listener.apply_env(&mut vars, &format!("SAPHIR_LISTENER_{}_", index))?;
+            if vars.0.len() < left {
+                referenced = referenced.max(index + 1);
+            }
+        }
+        // Listeners are only added for the variables naming a known setting, the others are
+        // reported below
+        self.listeners.truncate(referenced);
+
+        let middleware_vars = vars.0.keys().filter(|key| key.starts_with("SAPHIR_MIDDLEWARE_")).cloned().collect::<Vec<_>>();
+        for key in middleware_vars {
+            let (name, setting) = match key["SAPHIR_MIDDLEWARE_".len()..].split_once("__") {
+                Some((name, setting)) if !name.is_empty() && !setting.is_empty() => (name.to_lowercase(), setting.to_lowercase()),
+                _ => return Err(ConfigError::Env(key, "expected SAPHIR_MIDDLEWARE_<NAME>__<SETTING>".to_string())),
+            };
+
+            let value = vars.0.remove(&key).unwrap_or_default();
+            // Values are read as json when possible, e.g. numbers and booleans, and as strings otherwise
+            let value = serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value));
+            match self.middlewares.entry(name).or_insert_with(|| serde_json::Value::Object(Default::default())) {
+                serde_json::Value::Object(settings) => {
+                    settings.insert(setting, value);
+                }
+                _ => return Err(ConfigError::Env(key, "the middleware settings are not a map".to_string())),
+            }
+        }
+
+        match vars.0.into_keys().min() {
+            Some(key) => Err(ConfigError::Env(key, "not a known setting".to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Validate the settings without applying them
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(status) = self.panic_status {
+            validate_status("panic_status", status)?;
+        }
+
+        self.listener_builders().map(|_| ())
+    }
+
+    /// Deserialize the settings of the middleware named `name`, returning `None` if they are not
+    /// set
+    pub fn middleware<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>, ConfigError> {
+        self.middlewares.get(name)
+            .map(|settings| serde_json::from_value(settings.clone()))
+            .transpose()
+            .map_err(|e| ConfigError::Invalid(format!("middlewares.{}", name), e.to_string()))
+    }
+
+    /// Build a listener builder for every listener of the settings
+    pub fn listener_builders(&self) -> Result<Vec<ListenerBuilder>, ConfigError> {
+        self.listeners.iter().enumerate().map(|(index, listener)| {
+            listener.validate_address().and_then(|_| listener.configure(ListenerBuilder::new())).map_err(|e| match e {
+                ConfigError::Invalid(setting, msg) => ConfigError::Invalid(format!("listeners[{}].{}", index, setting), msg),
+                e => e,
+            })
+        }).collect()
+    }
+}
+
+/// Settings of a listener, see the [`ListenerBuilder`](../server/struct.ListenerBuilder.html)
+/// method of the same name for the meaning of each setting
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenerSettings {
+    /// TCP interface, as `host:port`
+    pub interface: Option<String>,
+    /// Path of a unix domain socket, instead of a TCP interface
+    #[cfg(unix)]
+    pub unix_socket: Option<PathBuf>,
+    /// File mode of the unix domain socket, e.g. `0o660`
+    #[cfg(unix)]
+    pub unix_socket_permissions: Option<u32>,
+    /// Maximum number of connections waiting to be accepted
+    pub listen_backlog: Option<i32>,
+    /// Set `SO_REUSEPORT` on the listening sockets
+    #[cfg(unix)]
+    pub reuse_port: Option<bool>,
+    /// Number of sockets bound to the TCP interface, requires `reuse_port`
+    #[cfg(unix)]
+    pub accept_loops: Option<usize>,
+    /// Set `IPV6_V6ONLY` on a listener bound to an IPv6 address
+    pub ipv6_only: Option<bool>,
+    /// Set `TCP_NODELAY` on the accepted connections
+    pub tcp_nodelay: Option<bool>,
+    /// Idle time before TCP keepalive probes are sent, 0 to disable
+    pub tcp_keepalive_ms: Option<u64>,
+    /// Time allowed to process a request, 0 to disable
+    pub request_timeout_ms: Option<u64>,
+    /// Status answered to a request which timed out
+    pub request_timeout_status: Option<u16>,
+    /// Time a connection can stay without any in-flight request, 0 to disable
+    pub idle_timeout_ms: Option<u64>,
+    /// Time allowed to receive a request head, 0 to disable
+    pub header_read_timeout_ms: Option<u64>,
+    /// Time allowed to receive a request body, 0 to disable
+    pub body_read_timeout_ms: Option<u64>,
+    /// Minimum body throughput, in bytes per second, 0 to disable
+    pub min_body_throughput: Option<u64>,
+    /// Maximum number of open connections, 0 for no limit
+    pub max_connections: Option<usize>,
+    /// Maximum number of open connections from a single ip address, 0 for no limit
+    pub max_connections_per_ip: Option<usize>,
+    /// `pause` or `reject`
+    pub connection_limit_behavior: Option<ConnectionLimitBehavior>,
+    /// Trusted upstreams, as ip addresses or CIDR blocks
+    pub proxy_protocol: Option<Vec<String>>,
+    /// `auto`, `http1_only` or `http2_only`
+    pub http_protocol: Option<HttpProtocol>,
+    /// Keep http1 connections alive between requests
+    pub http1_keep_alive: Option<bool>,
+    /// Maximum size of the http1 read buffer, in bytes
+    pub http1_max_buf_size: Option<usize>,
+    /// Maximum number of concurrent http2 streams per connection
+    pub http2_max_concurrent_streams: Option<u32>,
+    /// Interval at which http2 pings are sent, 0 to disable
+    pub http2_keep_alive_interval_ms: Option<u64>,
+    /// Time allowed to answer an http2 ping, requires `http2_keep_alive_interval_ms`
+    pub http2_keep_alive_timeout_ms: Option<u64>,
+    /// Tls settings, the listener serves https once a certificate is set
+    pub tls: TlsSettings,
+}
+
+impl ListenerSettings {
+    /// Fail if the listener is given nothing to bind, rather than binding every interface
+    fn validate_address(&self) -> Result<(), ConfigError> {
+        #[cfg(unix)]
+        let has_address = self.interface.is_some() || self.unix_socket.is_some();
+        #[cfg(not(unix))]
+        let has_address = self.interface.is_some();
+
+        if has_address {
+            Ok(())
+        } else {
+            Err(invalid("interface", "is required, or unix_socket on unix"))
+        }
+    }
+
+    /// Apply the settings to `builder`, failing if a setting is invalid
+    pub fn configure(&self, builder: ListenerBuilder) -> Result<ListenerBuilder, ConfigError> {
+        let mut l = builder;
+
+        if let Some(interface) = &self.interface {
+            match interface.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+                _ => return Err(invalid("interface", format!("expected host:port, got {:?}", interface))),
+            }
+            l = l.interface(interface);
+        }
+        #[cfg(unix)]
+        {
+            if let Some(path) = &self.unix_socket {
+                if self.interface.is_some() {
+                    return Err(invalid("unix_socket", "cannot be set along interface"));
+                }
+                l = l.unix_socket(path.clone());
+            }
+            if let Some(mode) = self.unix_socket_permissions {
+                if mode > 0o7777 {
+                    return Err(invalid("unix_socket_permissions", format!("{:o} is not a valid file mode", mode)));
+                }
+                l = l.unix_socket_permissions(mode);
+            }
+            if let Some(reuse_port) = self.reuse_port {
+                l = l.reuse_port(reuse_port);
+            }
+            if let Some(loops) = self.accept_loops {
+                if loops == 0 {
+                    return Err(invalid("accept_loops", "must be at least 1"));
+                }
+                if loops > 1 && self.reuse_port != Some(true) {
+                    return Err(invalid("accept_loops", "requires reuse_port to be enabled"));
+                }
+                if loops > 1 && self.unix_socket.is_some() {
+                    return Err(invalid("accept_loops", "cannot be set along unix_socket"));
+                }
+                l = l.accept_loops(loops);
+            }
+        }
+
+        if let Some(backlog) = self.listen_backlog {
+            if backlog <= 0 {
+                return Err(invalid("listen_backlog", "must be at least 1"));
+            }
+            l = l.listen_backlog(backlog);
+        }
+        if let Some(ipv6_only) = self.ipv6_only {
+            l = l.ipv6_only(ipv6_only);
+        }
+        if let Some(nodelay) = self.tcp_nodelay {
+            l = l.tcp_nodelay(nodelay);
+        }
+        if let Some(idle_ms) = self.tcp_keepalive_ms {
+            l = l.tcp_keepalive(non_zero(idle_ms));
+        }
+
+        if let Some(timeout_ms) = self.request_timeout_ms {
+            l = l.request_timeout(non_zero(timeout_ms));
+        }
+        if let Some(status) = self.request_timeout_status {
+            validate_status("request_timeout_status", status)?;
+            l = l.request_timeout_status(status);
+        }
+        if let Some(timeout_ms) = self.idle_timeout_ms {
+            l = l.idle_timeout(non_zero(timeout_ms));
+        }
+        if let Some(timeout_ms) = self.header_read_timeout_ms {
+            l = l.header_read_timeout(non_zero(timeout_ms));
+        }
+        if let Some(timeout_ms) = self.body_read_timeout_ms {
+            l = l.body_read_timeout(non_zero(timeout_ms));
+        }
+        if let Some(throughput) = self.min_body_throughput {
+            l = l.min_body_throughput(non_zero(throughput));
+        }
+
+        if let Some(max) = self.max_connections {
+            l = l.max_connections(non_zero(max));
+        }
+        if let Some(max) = self.max_connections_per_ip {
+            l = l.max_connections_per_ip(non_zero(max));
+        }
+        if let Some(behavior) = self.connection_limit_behavior {
+            l = l.connection_limit_behavior(behavior);
+        }
+        if let Some(upstreams) = &self.proxy_protocol {
+            l = l.proxy_protocol(upstreams);
+        }
+
+        if let Some(protocol) = self.http_protocol {
+            l = l.http_protocol(protocol);
+        }
+        if let Some(keep_alive) = self.http1_keep_alive {
+            l = l.http1_keep_alive(keep_alive);
+        }
+        if let Some(size) = self.http1_max_buf_size {
+            if size < MIN_HTTP1_MAX_BUF_SIZE {
+                return Err(invalid("http1_max_buf_size", format!("cannot be smaller than {} bytes", MIN_HTTP1_MAX_BUF_SIZE)));
+            }
+            l = l.http1_max_buf_size(size);
+        }
+        if let Some(max) = self.http2_max_concurrent_streams {
+            l = l.http2_max_concurrent_streams(max);
+        }
+        match (self.http2_keep_alive_interval_ms, self.http2_keep_alive_timeout_ms) {
+            (Some(interval_ms), timeout_ms) => {
+                l = l.http2_keep_alive(non_zero(interval_ms), timeout_ms.unwrap_or(DEFAULT_HTTP2_KEEP_ALIVE_TIMEOUT_MS));
+            }
+            (None, Some(_)) => return Err(invalid("http2_keep_alive_timeout_ms", "requires http2_keep_alive_interval_ms to be set")),
+            (None, None) => {}
+        }
+
+        self.tls.configure(l)
+    }
+
+    fn apply_env(&mut self, vars: &mut EnvVars, prefix: &str) -> Result<(), ConfigError> {
+        macro_rules! take_env {
+            ($settings:expr, $prefix:expr, [$($field:ident),+ $(,)?]) => {
+                $( vars.take(&format!("{}{}", $prefix, stringify!($field).to_uppercase()), &mut $settings.$field)?; )+
+            };
+        }
+
+        take_env!(self, prefix, [
+            interface, listen_backlog, ipv6_only, tcp_nodelay, tcp_keepalive_ms,
+            request_timeout_ms, request_timeout_status, idle_timeout_ms, header_read_timeout_ms,
+            body_read_timeout_ms, min_body_throughput, max_connections, max_connections_per_ip,
+            connection_limit_behavior, proxy_protocol, http_protocol, http1_keep_alive,
+            http1_max_buf_size, http2_max_concurrent_streams, http2_keep_alive_interval_ms,
+            http2_keep_alive_timeout_ms,
+        ]);
+        #[cfg(unix)]
+        {
+            take_env!(self, prefix, [unix_socket, reuse_port, accept_loops]);
+            let key = format!("{}UNIX_SOCKET_PERMISSIONS", prefix);
+            if let Some(mode) = vars.0.remove(&key) {
+                let mode = u32::from_str_radix(mode.trim().trim_start_matches("0o"), 8)
+                    .map_err(|_| ConfigError::Env(key, format!("{:?} is not an octal file mode", mode)))?;
+                self.unix_socket_permissions = Some(mode);
+            }
+        }
+
+        let tls_prefix = format!("{}TLS_", prefix);
+        take_env!(self.tls, tls_prefix, [
+            cert_path, key_path, key_passphrase, min_version, alpn_protocols, session_cache_size,
+            session_tickets, client_auth, client_ca_path, watch_interval_ms,
+        ]);
+
+        Ok(())
+    }
+}
+
+/// Tls version of the [`min_version`](struct.TlsSettings.html#structfield.min_version) setting
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum TlsVersion {
+    /// TLS 1.2, `1.2`
+    #[serde(rename = "1.2")]
+    Tls12,
+    /// TLS 1.3, `1.3`
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+/// Client certificate authentication policy of the
+/// [`client_auth`](struct.TlsSettings.html#structfield.client_auth) setting
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuthMode {
+    /// Client certificates are not requested, `none`
+    None,
+    /// Client certificates are verified when sent, `optional`
+    Optional,
+    /// Clients must send a valid certificate, `required`
+    Required,
+}
+
+/// Certificate used for a server name sent through SNI
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SniSettings {
+    /// Path of the PEM encoded certificate chain
+    pub cert_path: String,
+    /// Path of the PEM encoded private key
+    pub key_path: String,
+}
+
+/// Tls settings of a listener, requires saphir to be built with the `https` feature
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsSettings {
+    /// Path of the PEM encoded certificate chain
+    pub cert_path: Option<String>,
+    /// Path of the PEM encoded private key
+    pub key_path: Option<String>,
+    /// Passphrase of an encrypted private key
+    pub key_passphrase: Option<String>,
+    /// `1.2` or `1.3`
+    pub min_version: Option<TlsVersion>,
+    /// Protocols offered through ALPN, e.g. `["h2", "http/1.1"]`
+    pub alpn_protocols: Option<Vec<String>>,
+    /// Maximum number of sessions kept for resumption, 0 to disable
+    pub session_cache_size: Option<usize>,
+    /// Resume sessions through stateless tickets
+    pub session_tickets: Option<bool>,
+    /// `none`, `optional` or `required`, defaults to `required` when a `client_ca_path` is set
+    pub client_auth: Option<ClientAuthMode>,
+    /// Path of the PEM encoded CA bundle client certificates are verified against
+    pub client_ca_path: Option<String>,
+    /// Interval at which the certificate files are checked for modification
+    pub watch_interval_ms: Option<u64>,
+    /// Certificates by server name
+    pub sni: HashMap<String, SniSettings>,
+}
+
+impl TlsSettings {
+    fn is_set(&self) -> bool {
+        self.cert_path.is_some() || self.key_path.is_some() || self.key_passphrase.is_some() || self.min_version.is_some()
+            || self.alpn_protocols.is_some() || self.session_cache_size.is_some() || self.session_tickets.is_some()
+            || self.client_auth.is_some() || self.client_ca_path.is_some() || self.watch_interval_ms.is_some() || !self.sni.is_empty()
+    }
+
+    #[cfg(not(feature = "https"))]
+    fn configure(&self, builder: ListenerBuilder) -> Result<ListenerBuilder, ConfigError> {
+        if self.is_set() {
+            return Err(invalid("tls", "requires saphir to be built with the `https` feature"));
+        }
+
+        Ok(builder)
+    }
+
+    #[cfg(feature = "https")]
+    fn configure(&self, builder: ListenerBuilder) -> Result<ListenerBuilder, ConfigError> {
+        if !self.is_set() {
+            return Ok(builder);
+        }
+
+        let mut l = match (&self.cert_path, &self.key_path) {
+            (Some(cert_path), Some(key_path)) => builder.set_ssl_certificates(cert_path, key_path),
+            (Some(_), None) => return Err(invalid("tls.key_path", "is required along cert_path")),
+            (None, _) => return Err(invalid("tls.cert_path", "is required to enable tls")),
+        };
+
+        if let Some(passphrase) = &self.key_passphrase {
+            l = l.set_ssl_key_passphrase(passphrase);
+        }
+        if let Some(version) = self.min_version {
+            l = l.ssl_min_version(match version {
+                TlsVersion::Tls12 => rustls::ProtocolVersion::TLSv1_2,
+                TlsVersion::Tls13 => rustls::ProtocolVersion::TLSv1_3,
+            });
+        }
+        if let Some(protocols) = &self.alpn_protocols {
+            l = l.ssl_alpn_protocols(&protocols.iter().map(String::as_str).collect::<Vec<_>>());
+        }
+        if let Some(size) = self.session_cache_size {
+            l = l.ssl_session_cache_size(size);
+        }
+        if let Some(tickets) = self.session_tickets {
+            l = l.ssl_session_tickets(tickets);
+        }
+
+        let client_ca = self.client_ca_path.as_ref().map(|path| SslConfig::FilePath(path.clone()));
+        l = match (self.client_auth, client_ca) {
+            (None, None) | (Some(ClientAuthMode::None), None) => l,
+            (Some(ClientAuthMode::None), Some(_)) => return Err(invalid("tls.client_ca_path", "cannot be set when client_auth is none")),
+            (Some(ClientAuthMode::Optional), Some(ca)) => l.client_auth(ClientAuth::Optional(ca)),
+            (Some(ClientAuthMode::Required), Some(ca)) | (None, Some(ca)) => l.client_auth(ClientAuth::Required(ca)),
+            (Some(_), None) => return Err(invalid("tls.client_ca_path", "is required when client_auth is optional or required")),
+        };
+
+        if let Some(interval_ms) = self.watch_interval_ms {
+            l = l.watch_ssl_files(non_zero(interval_ms));
+        }
+
+        for (server_name, sni) in &self.sni {
+            l = l.add_sni_certificates(server_name, &sni.cert_path, &sni.key_path);
+        }
+
+        Ok(l)
+    }
+}
+
+/// Environment variables left to apply
+struct EnvVars(HashMap<String, String>);
+
+impl EnvVars {
+    /// Set `field` from the variable named `key`, if present
+    fn take<T: EnvValue>(&mut self, key: &str, field: &mut Option<T>) -> Result<(), ConfigError> {
+        if let Some(value) = self.0.remove(key) {
+            *field = Some(T::parse_env(value.trim()).map_err(|msg| ConfigError::Env(key.to_string(), msg))?);
+        }
+
+        Ok(())
+    }
+}
+
+/// Setting which can be read from an environment variable
+trait EnvValue: Sized {
+    fn parse_env(value: &str) -> Result<Self, String>;
+}
+
+macro_rules! impl_env_value_from_str {
+    ( $( $x:ty ),+ ) => {
+        $(
+            impl EnvValue for $x {
+                fn parse_env(value: &str) -> Result<Self, String> {
+                    <$x as FromStr>::from_str(value).map_err(|e| format!("{:?} is not valid: {}", value, e))
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! impl_env_value_deserialize {
+    ( $( $x:ty ),+ ) => {
+        $(
+            impl EnvValue for $x {
+                fn parse_env(value: &str) -> Result<Self, String> {
+                    let deserializer: StrDeserializer<'_, ValueError> = value.into_deserializer();
+                    <$x>::deserialize(deserializer).map_err(|e| e.to_string())
+                }
+            }
+        )+
+    }
+}
+
+impl_env_value_from_str!(String, PathBuf, bool, u16, u32, u64, i32, usize);
+impl_env_value_deserialize!(ConnectionLimitBehavior, HttpProtocol, TlsVersion, ClientAuthMode);
+
+impl EnvValue for Vec<String> {
+    fn parse_env(value: &str) -> Result<Self, String> {
+        Ok(value.split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect())
+    }
+}
+
+fn invalid<M: Into<String>>(setting: &str, msg: M) -> ConfigError {
+    ConfigError::Invalid(setting.to_string(), msg.into())
+}
+
+fn validate_status(setting: &str, status: u16) -> Result<(), ConfigError> {
+    StatusCode::from_u16(status).map(|_| ()).map_err(|_| invalid(setting, format!("{} is not a valid http status", status)))
+}
+
+/// Timeouts and limits set to 0 are disabled
+fn non_zero<T: Default + PartialEq>(value: T) -> Option<T> {
+    if value == T::default() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn parse_toml() {
+        let settings = ServerSettings::parse(r#"
+            shutdown_timeout_ms = 10000
+
+            [[listeners]]
+            interface = "0.0.0.0:80"
+            connection_limit_behavior = "reject"
+            proxy_protocol = ["10.0.0.0/8"]
+
+            [middlewares.log]
+            prefix = "API"
+        "#, ConfigFormat::Toml).unwrap();
+
+        assert_eq!(settings.shutdown_timeout_ms, Some(10000));
+        assert_eq!(settings.listeners[0].interface.as_deref(), Some("0.0.0.0:80"));
+        assert_eq!(settings.listeners[0].connection_limit_behavior, Some(ConnectionLimitBehavior::Reject));
+        assert_eq!(settings.listeners[0].proxy_protocol, Some(vec!["10.0.0.0/8".to_string()]));
+        assert_eq!(settings.middlewares["log"]["prefix"], "API");
+        settings.validate().unwrap();
+    }
+
+    #[test]
+    fn parse_yaml() {
+        let settings = ServerSettings::parse("
+panic_status: 503
+listeners:
+  - interface: 127.0.0.1:8080
+    http_protocol: http1_only
+    tls:
+      min_version: '1.3'
+", ConfigFormat::Yaml).unwrap();
+
+        assert_eq!(settings.panic_status, Some(503));
+        assert_eq!(settings.listeners[0].interface.as_deref(), Some("127.0.0.1:8080"));
+        assert_eq!(settings.listeners[0].http_protocol, Some(HttpProtocol::Http1Only));
+        assert_eq!(settings.listeners[0].tls.min_version, Some(TlsVersion::Tls13));
+    }
+
+    #[test]
+    fn parse_json() {
+        let settings = ServerSettings::parse(r#"{
+            "listeners": [{ "interface": "127.0.0.1:8080", "request_timeout_ms": 0 }, { "interface": "127.0.0.1:8081" }],
+            "middlewares": { "auth": { "keys": ["a", "b"] } }
+        }"#, ConfigFormat::Json).unwrap();
+
+        assert_eq!(settings.listeners.len(), 2);
+        assert_eq!(settings.listeners[0].request_timeout_ms, Some(0));
+        assert_eq!(settings.middleware::<HashMap<String, Vec<String>>>("auth").unwrap().unwrap()["keys"], vec!["a", "b"]);
+        assert!(settings.middleware::<HashMap<String, Vec<String>>>("log").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = ServerSettings::parse("[[listeners]]\ninterfac = \"0.0.0.0:80\"\n", ConfigFormat::Toml).unwrap_err();
+        assert!(matches!(error, ConfigError::Parse(_, _)));
+        let message = error.to_string();
+        assert!(message.starts_with("cannot parse configuration: ") && message.contains("interfac"), "{}", message);
+
+        assert!(matches!(ServerSettings::parse("listeners: 3", ConfigFormat::Yaml), Err(ConfigError::Parse(_, _))));
+        assert!(matches!(ServerSettings::parse("{", ConfigFormat::Json), Err(ConfigError::Parse(_, _))));
+        assert!(matches!(ServerSettings::from_file("saphir.ini"), Err(ConfigError::UnknownFormat(_))));
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let invalid = |content: &str| ServerSettings::parse(content, ConfigFormat::Toml).unwrap().validate().unwrap_err().to_string();
+
+        assert_eq!(invalid("panic_status = 1000"), "invalid setting panic_status: 1000 is not a valid http status");
+        assert_eq!(
+            invalid("[[listeners]]\ninterface = \"localhost\""),
+            "invalid setting listeners[0].interface: expected host:port, got \"localhost\""
+        );
+        assert_eq!(
+            invalid("[[listeners]]\ninterface = \"0.0.0.0:80\"\n[[listeners]]\ninterface = \"0.0.0.0:81\"\nlisten_backlog = 0"),
+            "invalid setting listeners[1].listen_backlog: must be at least 1"
+        );
+        assert_eq!(
+            invalid("[[listeners]]\ninterface = \"0.0.0.0:80\"\nhttp2_keep_alive_timeout_ms = 10"),
+            "invalid setting listeners[0].http2_keep_alive_timeout_ms: requires http2_keep_alive_interval_ms to be set"
+        );
+
+        let settings = ServerSettings::parse("[middlewares.log]\nprefix = 3", ConfigFormat::Toml).unwrap();
+        let error = settings.middleware::<HashMap<String, String>>("log").unwrap_err().to_string();
+        assert!(error.starts_with("invalid setting middlewares.log: "), "{}", error);
+    }
+
+    #[test]
+    fn env_overrides_the_first_listener() {
+        let mut settings = ServerSettings::parse("[[listeners]]\ninterface = \"0.0.0.0:80\"\nrequest_timeout_ms = 1000", ConfigFormat::Toml).unwrap();
+        settings.apply_env_vars(env(&[("SAPHIR_LISTENER_REQUEST_TIMEOUT_MS", "2000"), ("SAPHIR_LISTENER_TLS_SESSION_TICKETS", "true")])).unwrap();
+        assert_eq!(settings.listeners.len(), 1);
+        assert_eq!(settings.listeners[0].request_timeout_ms, Some(2000));
+        assert_eq!(settings.listeners[0].tls.session_tickets, Some(true));
+
+        settings.apply_env_vars(env(&[("SAPHIR_LISTENER_0_REQUEST_TIMEOUT_MS", "3000"), ("SAPHIR_LISTENER_0_PROXY_PROTOCOL", "10.0.0.1, 10.0.0.2")])).unwrap();
+        assert_eq!(settings.listeners.len(), 1);
+        assert_eq!(settings.listeners[0].request_timeout_ms, Some(3000));
+        assert_eq!(settings.listeners[0].proxy_protocol, Some(vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()]));
+        assert_eq!(settings.listeners[0].interface.as_deref(), Some("0.0.0.0:80"));
+    }
+
+    #[test]
+    fn env_adds_listeners() {
+        let mut settings = ServerSettings::default();
+        settings.apply_env_vars(env(&[("SAPHIR_LISTENER_1_INTERFACE", "127.0.0.1:81"), ("SAPHIR_LISTENER_INTERFACE", "127.0.0.1:80")])).unwrap();
+        assert_eq!(settings.listeners.len(), 2);
+        assert_eq!(settings.listeners[0].interface.as_deref(), Some("127.0.0.1:80"));
+        assert_eq!(settings.listeners[1].interface.as_deref(), Some("127.0.0.1:81"));
+        settings.validate().unwrap();
+    }
+
+    #[test]
+    fn env_invalid_value() {
+        let mut settings = ServerSettings::default();
+        let error = settings.apply_env_vars(env(&[("SAPHIR_LISTENER_MAX_CONNECTIONS", "many")])).unwrap_err();
+        assert!(matches!(error, ConfigError::Env(ref key, _) if key == "SAPHIR_LISTENER_MAX_CONNECTIONS"));
+
+        let error = settings.apply_env_vars(env(&[("SAPHIR_LISTENER_CONNECTION_LIMIT_BEHAVIOR", "drop")])).unwrap_err();
+        assert!(error.to_string().starts_with("invalid environment variable SAPHIR_LISTENER_CONNECTION_LIMIT_BEHAVIOR: "), "{}", error);
+    }
+
+    #[test]
+    fn env_middleware_settings() {
+        let mut settings = ServerSettings::default();
+        settings.apply_env_vars(env(&[("SAPHIR_MIDDLEWARE_LOG__PREFIX", "API"), ("SAPHIR_MIDDLEWARE_LOG__LEVEL", "3")])).unwrap();
+        assert_eq!(settings.middlewares["log"]["prefix"], "API");
+        assert_eq!(settings.middlewares["log"]["level"], 3);
+
+        for key in &["SAPHIR_MIDDLEWARE_LOG_PREFIX", "SAPHIR_MIDDLEWARE___PREFIX", "SAPHIR_MIDDLEWARE_LOG__"] {
+            let error = ServerSettings::default().apply_env_vars(env(&[(key, "API")])).unwrap_err();
+            assert_eq!(error.to_string(), format!("invalid environment variable {}: expected SAPHIR_MIDDLEWARE_<NAME>__<SETTING>", key));
+        }
+
+        let mut settings = ServerSettings::parse(r#"middlewares = { log = "API" }"#, ConfigFormat::Toml).unwrap();
+        let error = settings.apply_env_vars(env(&[("SAPHIR_MIDDLEWARE_LOG__PREFIX", "API")])).unwrap_err();
+        assert_eq!(error.to_string(), "invalid environment variable SAPHIR_MIDDLEWARE_LOG__PREFIX: the middleware settings are not a map");
+    }
+
+    #[test]
+    fn env_unknown_setting_is_rejected() {
+        let mut settings = ServerSettings::default();
+        let error = settings.apply_env_vars(env(&[("SAPHIR_LISTENER_3_INTERFCE", "127.0.0.1:80")])).unwrap_err();
+        assert_eq!(error.to_string(), "invalid environment variable SAPHIR_LISTENER_3_INTERFCE: not a known setting");
+        // No listener is added for a misspelled setting
+        assert!(settings.listeners.is_empty());
+
+        let mut settings = ServerSettings::default();
+        assert!(settings.apply_env_vars(env(&[("SAPHIR_LISTENER_INTERFCE", "127.0.0.1:80")])).is_err());
+        assert!(settings.apply_env_vars(env(&[("SAPHIR_SHUTDOWN_TIMEOUT", "10")])).is_err());
+        assert!(settings.listeners.is_empty());
+    }
+
+    #[test]
+    fn env_upgrade_variables_are_not_settings() {
+        let mut settings = ServerSettings::default();
+        settings.apply_env_vars(env(&[("SAPHIR_UPGRADE_FDS", "3,4;5"), ("SAPHIR_UPGRADE_READY_FD", "6"), ("PATH", "/bin")])).unwrap();
+        assert!(settings.listeners.is_empty());
+        assert!(settings.middlewares.is_empty());
+    }
+
+    #[test]
+    fn listener_without_address_is_rejected() {
+        let settings = ServerSettings::parse("[[listeners]]\nrequest_timeout_ms = 1000\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(settings.validate().unwrap_err().to_string(), "invalid setting listeners[0].interface: is required, or unix_socket on unix");
+
+        // A listener between two referenced by the environment is left without an address
+        let mut settings = ServerSettings::default();
+        settings.apply_env_vars(env(&[("SAPHIR_LISTENER_INTERFACE", "127.0.0.1:80"), ("SAPHIR_LISTENER_2_INTERFACE", "127.0.0.1:81")])).unwrap();
+        assert_eq!(settings.listeners.len(), 3);
+        assert_eq!(settings.validate().unwrap_err().to_string(), "invalid setting listeners[1].interface: is required, or unix_socket on unix");
+    }
+}
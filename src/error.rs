@@ -12,7 +12,11 @@ pub enum InternalError {
 }
 
 /// Error type throughout the saphir stack
+///
+/// Some variants only exist when their feature is enabled, so matching on this type needs a
+/// wildcard arm for the features enabled elsewhere in the dependency graph.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum SaphirError {
     ///
     Internal(InternalError),
@@ -22,6 +26,59 @@ pub enum SaphirError {
     Custom(Box<dyn StdError + Send + Sync + 'static>),
     ///
     Other(String),
+    /// Using Feature `https`
+    ///
+    /// Invalid tls configuration
+    #[cfg(feature = "https")]
+    Ssl(SslError),
+    /// Using Feature `config`
+    ///
+    /// Invalid configuration file or environment
+    #[cfg(feature = "config")]
+    Config(ConfigError),
+}
+
+/// Using Feature `https`
+///
+/// Error raised while loading the certificates and keys of a tls listener. The `String` carried by
+/// the variants describes where the certificate or key was loaded from.
+#[cfg(feature = "https")]
+#[derive(Debug)]
+pub enum SslError {
+    /// A certificate or key file could not be read
+    File(String, IoError),
+    /// No valid PEM encoded certificate was found
+    InvalidCertificate(String),
+    /// No valid PEM encoded private key was found
+    InvalidKey(String),
+    /// The private key is encrypted and the passphrase is missing or wrong, or it uses the legacy
+    /// openssl encryption of RSA and EC keys, which is not supported
+    EncryptedKey(String),
+    /// The private key is not a RSA, ECDSA P-256 or ECDSA P-384 key
+    UnsupportedKey(String),
+    /// The private key does not match the certificate
+    KeyMismatch(String),
+    /// The tls configuration of the listener is incomplete or inconsistent
+    InvalidConfig(String),
+}
+
+/// Using Feature `config`
+///
+/// Error raised while loading the settings of a server from a file or from the environment
+#[cfg(feature = "config")]
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read
+    File(String, IoError),
+    /// The format of the configuration file cannot be told from its extension
+    UnknownFormat(String),
+    /// The configuration file is not valid, with the message of the parser
+    Parse(String, String),
+    /// An environment variable has an invalid value or is not a known setting
+    Env(String, String),
+    /// A setting has an invalid value, the setting being named after its path, e.g.
+    /// `listeners[0].request_timeout_status`
+    Invalid(String, String),
 }
 
 impl From<HttpCrateError> for SaphirError {
@@ -42,10 +99,78 @@ impl From<IoError> for SaphirError {
     }
 }
 
+#[cfg(feature = "https")]
+impl From<SslError> for SaphirError {
+    fn from(e: SslError) -> Self {
+        SaphirError::Ssl(e)
+    }
+}
+
+#[cfg(feature = "config")]
+impl From<ConfigError> for SaphirError {
+    fn from(e: ConfigError) -> Self {
+        SaphirError::Config(e)
+    }
+}
+
 impl Display for SaphirError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
-        f.write_str("saphirError")
+        match self {
+            #[cfg(feature = "https")]
+            SaphirError::Ssl(e) => e.fmt(f),
+            #[cfg(feature = "config")]
+            SaphirError::Config(e) => e.fmt(f),
+            _ => f.write_str("saphirError"),
+        }
     }
 }
 
-impl StdError for SaphirError {}
\ No newline at end of file
+impl StdError for SaphirError {}
+
+#[cfg(feature = "https")]
+impl Display for SslError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            SslError::File(source, e) => write!(f, "cannot read {}: {}", source, e),
+            SslError::InvalidCertificate(source) => write!(f, "no valid certificate found in {}", source),
+            SslError::InvalidKey(source) => write!(f, "no valid private key found in {}", source),
+            SslError::EncryptedKey(source) => write!(f, "cannot decrypt the private key in {}, the passphrase is missing or wrong", source),
+            SslError::UnsupportedKey(source) => write!(f, "unsupported private key type in {}", source),
+            SslError::KeyMismatch(source) => write!(f, "the private key in {} does not match its certificate", source),
+            SslError::InvalidConfig(msg) => f.write_str(msg),
+        }
+    }
+}
+
+#[cfg(feature = "https")]
+impl StdError for SslError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            SslError::File(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "config")]
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            ConfigError::File(path, e) => write!(f, "cannot read {}: {}", path, e),
+            ConfigError::UnknownFormat(path) => write!(f, "unknown format for {}, expected a .toml, .yaml, .yml or .json file", path),
+            ConfigError::Parse(source, msg) => write!(f, "cannot parse {}: {}", source, msg),
+            ConfigError::Env(var, msg) => write!(f, "invalid environment variable {}: {}", var, msg),
+            ConfigError::Invalid(setting, msg) => write!(f, "invalid setting {}: {}", setting, msg),
+        }
+    }
+}
+
+#[cfg(feature = "config")]
+impl StdError for ConfigError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ConfigError::File(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -56,12 +56,22 @@ pub mod middleware;
 pub mod guard;
 ///
 pub mod controller;
+/// In-process client to test a server stack without sockets
+pub mod testing;
+/// Using Feature `config`
+///
+/// Settings loaded from configuration files and environment variables
+#[cfg(feature = "config")]
+pub mod config;
 ///
 pub use cookie;
 ///
 pub use http;
 ///
 pub use hyper;
+/// Using Feature `https`
+#[cfg(feature = "https")]
+pub use rustls;
 
 /// Contains everything you need to bootstrap your http server
 ///
@@ -99,6 +109,8 @@ pub mod prelude {
     pub use crate::middleware::MiddlewareChain;
     ///
     pub use crate::request::Request;
+    /// Address of the peer which sent a request
+    pub use crate::request::PeerAddr;
     ///
     pub use crate::response::Builder;
     ///
@@ -38,7 +38,7 @@
 //!
 //! Inside the middleware chain we need a little bit of unsafe code. This code allow us to consider
 //! the futures generated by the middlewares as 'static. This is considered safe since all
-//! middleware data lives within the server stack which has a static lifetime over your application.
+//! middleware data lives within the server stack, which is leaked once the server runs and is never freed.
 //! We plan to remove this unsafe code as soon as we find another solution to it.
 
 use crate::{error::SaphirError, http_context::HttpContext, response::Response, utils::UriPathMatcher};
@@ -70,7 +70,7 @@ where
         chain: &dyn MiddlewareChain,
     ) -> BoxFuture<'static, Result<Response<Body>, SaphirError>> {
         // # SAFETY #
-        // The middleware chain and data are initialized in static memory when calling run on Server.
+        // The middleware chain and data are owned by the server stack, which is leaked and never freed.
         let (data, chain) = unsafe {
             (
                 std::mem::transmute::<&'_ Data, &'static Data>(data),
@@ -4,11 +4,151 @@ use std::ops::{Deref, DerefMut};
 use cookie::Cookie;
 use cookie::CookieJar;
 use http::Request as RawRequest;
+use http_body::SizeHint;
+use hyper::body::{Body, HttpBody};
 
 use crate::utils::UriPathMatcher;
-use std::net::SocketAddr;
+use std::fmt::{Display, Formatter, Error as FmtError};
+use std::net::{IpAddr, SocketAddr};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use futures::channel::oneshot;
+use futures::future::{FutureExt, Shared};
 use futures_util::future::Future;
 
+/// Address of the peer which sent a request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PeerAddr {
+    /// Peer connected through a TCP socket
+    Tcp(SocketAddr),
+    /// Peer connected through a unix domain socket, with the path it is bound to if any
+    #[cfg(unix)]
+    Unix(Option<PathBuf>),
+}
+
+impl PeerAddr {
+    /// Return the TCP socket address of the peer, if it is connected through TCP
+    #[inline]
+    pub fn as_tcp(&self) -> Option<&SocketAddr> {
+        match self {
+            PeerAddr::Tcp(addr) => Some(addr),
+            #[cfg(unix)]
+            PeerAddr::Unix(_) => None,
+        }
+    }
+
+    /// Return the ip address of the peer, if it is connected through TCP
+    #[inline]
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.as_tcp().map(|addr| addr.ip())
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+impl Display for PeerAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            PeerAddr::Tcp(addr) => addr.fmt(f),
+            #[cfg(unix)]
+            PeerAddr::Unix(Some(path)) => write!(f, "unix:{}", path.display()),
+            #[cfg(unix)]
+            PeerAddr::Unix(None) => f.write_str("unix:(unnamed)"),
+        }
+    }
+}
+
+/// Token notified once the client which sent a request disconnects before it was answered.
+///
+/// The handler of a request is cancelled once its client disconnects and the middlewares receive
+/// a response with the [`CLIENT_CLOSED_REQUEST_STATUS`](../server/constant.CLIENT_CLOSED_REQUEST_STATUS.html)
+/// instead. The token can be cloned and moved into work spawned by the handler, to stop it as well.
+///
+/// ```rust
+///# use saphir::prelude::*;
+///# async fn build_report() {}
+/// async fn report_handler(req: Request<Body>) -> u16 {
+///     let disconnect = req.disconnect_token().clone();
+///     tokio::spawn(async move {
+///         futures::future::select(Box::pin(build_report()), Box::pin(disconnect.disconnected())).await;
+///     });
+///     202
+/// }
+/// ```
+#[derive(Clone)]
+pub struct DisconnectToken {
+    /// Unset for a request built outside of a server, which never disconnects
+    inner: Option<DisconnectState>,
+}
+
+#[derive(Clone)]
+struct DisconnectState {
+    disconnected: Arc<AtomicBool>,
+    notified: Shared<oneshot::Receiver<()>>,
+}
+
+impl DisconnectToken {
+    pub(crate) fn new() -> (DisconnectToken, DisconnectNotifier) {
+        let disconnected = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = oneshot::channel();
+        let token = DisconnectToken {
+            inner: Some(DisconnectState {
+                disconnected: disconnected.clone(),
+                notified: receiver.shared(),
+            }),
+        };
+
+        (token, DisconnectNotifier { disconnected, sender: Some(sender) })
+    }
+
+    /// Token of a request which is never disconnected
+    pub(crate) fn never() -> DisconnectToken {
+        DisconnectToken { inner: None }
+    }
+
+    /// Return true if the client has disconnected
+    #[inline]
+    pub fn is_disconnected(&self) -> bool {
+        self.inner.as_ref().map(|inner| inner.disconnected.load(Ordering::SeqCst)).unwrap_or(false)
+    }
+
+    /// Resolve once the client has disconnected, never resolve if the request is answered first
+    pub async fn disconnected(&self) {
+        match &self.inner {
+            Some(inner) if inner.notified.clone().await.is_ok() => {}
+            _ => futures::future::pending::<()>().await,
+        }
+    }
+}
+
+/// Notify the disconnect token of a request once dropped, unless the request was answered
+pub(crate) struct DisconnectNotifier {
+    disconnected: Arc<AtomicBool>,
+    sender: Option<oneshot::Sender<()>>,
+}
+
+impl DisconnectNotifier {
+    /// The request was answered, the token is never notified
+    pub(crate) fn answered(mut self) {
+        self.sender.take();
+    }
+}
+
+impl Drop for DisconnectNotifier {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            self.disconnected.store(true, Ordering::SeqCst);
+            let _ = sender.send(());
+        }
+    }
+}
+
 /// Struct that wraps a hyper request + some magic
 pub struct Request<T> {
     #[doc(hidden)]
@@ -20,12 +160,21 @@ pub struct Request<T> {
     #[doc(hidden)]
     cookies: CookieJar,
     #[doc(hidden)]
-    peer_addr: Option<SocketAddr>,
+    peer_addr: Option<PeerAddr>,
+    #[doc(hidden)]
+    pub(crate) destination_addr: Option<SocketAddr>,
+    #[doc(hidden)]
+    #[cfg(feature = "https")]
+    pub(crate) peer_certificates: Option<Arc<Vec<rustls::Certificate>>>,
+    #[doc(hidden)]
+    pub(crate) disconnect: DisconnectToken,
+    #[doc(hidden)]
+    pub(crate) body_size_hint: Option<SizeHint>,
 }
 
 impl<T> Request<T> {
     #[doc(hidden)]
-    pub fn new(raw: RawRequest<T>, peer_addr: Option<SocketAddr>) -> Self {
+    pub fn new(raw: RawRequest<T>, peer_addr: Option<PeerAddr>) -> Self {
         let mut cp = raw.uri().path().to_owned().split('/').map(|s| s.to_owned()).collect::<VecDeque<String>>();
         cp.pop_front();
         if cp.back().map(|s| s.len()).unwrap_or(0) < 1 {
@@ -36,22 +185,59 @@ impl<T> Request<T> {
             current_path: cp,
             captures: Default::default(),
             cookies: Default::default(),
-            peer_addr
+            peer_addr,
+            destination_addr: None,
+            #[cfg(feature = "https")]
+            peer_certificates: None,
+            disconnect: DisconnectToken::never(),
+            body_size_hint: None,
         }
     }
 
-    /// Return the Peer SocketAddr if one was available when receiving the request
+    /// Return the Peer address if one was available when receiving the request
     #[inline]
-    pub fn peer_addr(&self) -> Option<&SocketAddr> {
+    pub fn peer_addr(&self) -> Option<&PeerAddr> {
         self.peer_addr.as_ref()
     }
 
     ///
     #[inline]
-    pub fn peer_addr_mut(&mut self) -> Option<&mut SocketAddr> {
+    pub fn peer_addr_mut(&mut self) -> Option<&mut PeerAddr> {
         self.peer_addr.as_mut()
     }
 
+    /// Return the address the client originally connected to, when the request was forwarded by a
+    /// trusted proxy through the PROXY protocol. See
+    /// [`proxy_protocol`](../server/struct.ListenerBuilder.html#method.proxy_protocol).
+    #[inline]
+    pub fn destination_addr(&self) -> Option<&SocketAddr> {
+        self.destination_addr.as_ref()
+    }
+
+    /// Using Feature `https`
+    ///
+    /// Return the certificate chain presented by the peer, if the request was received on a tls
+    /// listener configured with [`client_auth`](../server/struct.ListenerBuilder.html#method.client_auth).
+    /// The chain has been verified against the listener CA bundle, starting with the peer certificate.
+    #[inline]
+    #[cfg(feature = "https")]
+    pub fn peer_certificates(&self) -> Option<&[rustls::Certificate]> {
+        self.peer_certificates.as_ref().map(|certs| certs.as_slice())
+    }
+
+    /// Return the token notified once the client which sent this request disconnects, see
+    /// [`DisconnectToken`](struct.DisconnectToken.html)
+    #[inline]
+    pub fn disconnect_token(&self) -> &DisconnectToken {
+        &self.disconnect
+    }
+
+    /// Return true if the client which sent this request has disconnected
+    #[inline]
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnect.is_disconnected()
+    }
+
     /// Get the cookies sent by the browsers.
     ///
     /// Before accessing cookies, you will need to parse them, it is done with the
@@ -125,13 +311,18 @@ impl<T> Request<T> {
         where
             F: FnOnce(T) -> U,
     {
-        let Request { inner, current_path, captures, cookies, peer_addr } = self;
+        let Request { inner, current_path, captures, cookies, peer_addr, destination_addr, #[cfg(feature = "https")] peer_certificates, disconnect, body_size_hint } = self;
         Request {
             inner: inner.map(f),
             current_path,
             captures,
             cookies,
-            peer_addr
+            peer_addr,
+            destination_addr,
+            #[cfg(feature = "https")]
+            peer_certificates,
+            disconnect,
+            body_size_hint,
         }
     }
 
@@ -150,7 +341,7 @@ impl<T> Request<T> {
             F: FnOnce(T) -> Fut,
             Fut: Future<Output=U>
     {
-        let Request { inner, current_path, captures, cookies, peer_addr } = self;
+        let Request { inner, current_path, captures, cookies, peer_addr, destination_addr, #[cfg(feature = "https")] peer_certificates, disconnect, body_size_hint } = self;
         let (head, body) = inner.into_parts();
         let mapped = f(body).await;
         let mapped_r = RawRequest::from_parts(head, mapped);
@@ -160,10 +351,20 @@ impl<T> Request<T> {
             current_path,
             captures,
             cookies,
-            peer_addr
+            peer_addr,
+            destination_addr,
+            #[cfg(feature = "https")]
+            peer_certificates,
+            disconnect,
+            body_size_hint,
         }
     }
 
+    /// Segments of the path not consumed by the router yet
+    pub(crate) fn current_path(&self) -> &VecDeque<String> {
+        &self.current_path
+    }
+
     pub(crate) fn current_path_match_all(&mut self, path: &UriPathMatcher) -> bool {
         if path.len() != self.current_path.len() {
             return false;
@@ -181,18 +382,20 @@ impl<T> Request<T> {
             }
         }
 
-        // Alter current path and capture path variable
-        {
-            for seg in path.iter() {
-                if let Some(current) = self.current_path.pop_front() {
-                    if let Some(name) = seg.name() {
-                        self.captures.insert(name.to_string(), current);
-                    }
+        self.capture_path(path);
+        true
+    }
+
+    /// Consume the current path, capturing its segments matched by the variables of `path`. The
+    /// current path must already be known to match `path`.
+    pub(crate) fn capture_path(&mut self, path: &UriPathMatcher) {
+        for seg in path.iter() {
+            if let Some(current) = self.current_path.pop_front() {
+                if let Some(name) = seg.name() {
+                    self.captures.insert(name.to_string(), current);
                 }
             }
         }
-
-        true
     }
 
     /// Parse cookies from the Cookie header
@@ -206,6 +409,25 @@ impl<T> Request<T> {
     }
 }
 
+impl Request<Body> {
+    /// Return the size hint of the body, as received by the server.
+    ///
+    /// The server wraps the body of a request to enforce the
+    /// [`body_read_timeout`](../server/struct.ListenerBuilder.html#method.body_read_timeout) and
+    /// [`min_body_throughput`](../server/struct.ListenerBuilder.html#method.min_body_throughput) of
+    /// its listener, which hides the size hint of the body itself. This one is kept.
+    ///
+    /// ```rust
+    ///# use saphir::prelude::*;
+    ///# use hyper::Request as RawRequest;
+    ///# let req = Request::new(RawRequest::builder().method("POST").uri("https://www.rust-lang.org/").body(Body::from("hello")).unwrap(), None);
+    /// assert_eq!(req.body_size_hint().exact(), Some(5));
+    /// ```
+    pub fn body_size_hint(&self) -> SizeHint {
+        self.body_size_hint.clone().unwrap_or_else(|| self.inner.body().size_hint())
+    }
+}
+
 impl<T, E> Request<Result<T, E>> {
     /// Convert a request of Result<T, E> in a Result<Request<T>, E>
     ///
@@ -219,7 +441,7 @@ impl<T, E> Request<Result<T, E>> {
     /// assert!(res.is_ok());
     /// ```
     pub fn transpose(self) -> Result<Request<T>, E> {
-        let Request { inner, current_path, captures, cookies, peer_addr } = self;
+        let Request { inner, current_path, captures, cookies, peer_addr, destination_addr, #[cfg(feature = "https")] peer_certificates, disconnect, body_size_hint } = self;
         let (head, body) = inner.into_parts();
 
         body.map(move |b| {
@@ -228,7 +450,12 @@ impl<T, E> Request<Result<T, E>> {
                 current_path,
                 captures,
                 cookies,
-                peer_addr
+                peer_addr,
+                destination_addr,
+                #[cfg(feature = "https")]
+                peer_certificates,
+                disconnect,
+                body_size_hint,
             }
         })
     }
@@ -246,7 +473,7 @@ impl<T> Request<Option<T>> {
     /// assert!(opt.is_some());
     /// ```
     pub fn transpose(self) -> Option<Request<T>> {
-        let Request { inner, current_path, captures, cookies, peer_addr } = self;
+        let Request { inner, current_path, captures, cookies, peer_addr, destination_addr, #[cfg(feature = "https")] peer_certificates, disconnect, body_size_hint } = self;
         let (head, body) = inner.into_parts();
 
         body.map(move |b| {
@@ -255,7 +482,12 @@ impl<T> Request<Option<T>> {
                 current_path,
                 captures,
                 cookies,
-                peer_addr
+                peer_addr,
+                destination_addr,
+                #[cfg(feature = "https")]
+                peer_certificates,
+                disconnect,
+                body_size_hint,
             }
         })
     }
@@ -4,7 +4,7 @@
 //!
 //! Inside the router we need a little bit of unsafe code. This code allow us to consider
 //! the futures generated by the handlers as 'static. This is considered safe since all
-//! data lives within the server stack which has a static lifetime over your application.
+//! data lives within the server stack, which is leaked once the server runs and is never freed.
 //! We plan to remove this unsafe code as soon as we find another solution to it.
 
 use crate::{
@@ -14,10 +14,11 @@ use crate::{
     request::Request,
     responder::{DynResponder, Responder},
     response::Response,
-    utils::{EndpointResolver, EndpointResolverResult},
+    server::CLIENT_CLOSED_REQUEST_STATUS,
+    utils::{EndpointResolver, RouteTree},
     guard::{Builder as GuardBuilder, GuardChain, GuardChainEnd},
 };
-use futures::{future::BoxFuture, FutureExt};
+use futures::{future::{self, BoxFuture, Either}, FutureExt};
 use http::Method;
 use hyper::Body;
 use std::{collections::HashMap, sync::Arc};
@@ -55,6 +56,40 @@ impl<Controllers: 'static + RouterChain + Unpin + Send + Sync> Builder<Controlle
     /// builder.route("/simple", Method::GET, simple_handler);
     /// // ...
     /// ```
+    ///
+    /// A path segment is either static, a variable like `{name}` or a variable matching a regex
+    /// like `{id#r(^[0-9]+$)}`. When several routes match a request, static segments win over
+    /// regex ones and regex ones over variables, segment by segment from the start of the path.
+    ///
+    /// ```rust
+    ///# use saphir::prelude::*;
+    /// use saphir::testing::TestServer;
+    ///
+    /// async fn me(_req: Request<Body>) -> (u16, String) { (200, "me".to_string()) }
+    /// async fn by_id(req: Request<Body>) -> (u16, String) { (200, format!("id {}", req.captures()["id"])) }
+    /// async fn by_name(req: Request<Body>) -> (u16, String) { (200, format!("name {}", req.captures()["name"])) }
+    /// async fn posts(req: Request<Body>) -> (u16, String) { (200, format!("posts of {}", req.captures()["name"])) }
+    ///
+    ///# #[tokio::main]
+    ///# async fn main() -> Result<(), SaphirError> {
+    /// let server = TestServer::new(Server::builder()
+    ///     .configure_router(|r| {
+    ///         r.route("/users/{name}", Method::GET, by_name)
+    ///             .route("/users/{name}/posts", Method::GET, posts)
+    ///             .route("/users/{id#r(^[0-9]+$)}", Method::GET, by_id)
+    ///             .route("/users/me", Method::GET, me)
+    ///     })
+    ///     .build());
+    ///
+    /// let client = server.client();
+    /// client.get("/users/me").send().await?.assert_body("me");
+    /// client.get("/users/42").send().await?.assert_body("id 42");
+    /// client.get("/users/bob").send().await?.assert_body("name bob");
+    /// client.get("/users/me/posts").send().await?.assert_body("posts of me");
+    /// client.post("/users/me").send().await?.assert_status(405);
+    ///# Ok(())
+    ///# }
+    /// ```
     pub fn route<H>(mut self, route: &str, method: Method, handler: H) -> Self
     where
         H: 'static + DynHandler<Body> + Send + Sync,
@@ -174,7 +209,7 @@ impl<Controllers: 'static + RouterChain + Unpin + Send + Sync> Builder<Controlle
 
         Router {
             inner: Arc::new(RouterInner {
-                resolvers: resolver.into_iter().map(|(_, e)| e).collect(),
+                routes: RouteTree::new(resolver.into_iter().map(|(_, e)| e)),
                 chain: Box::new(controllers),
             }),
         }
@@ -182,7 +217,7 @@ impl<Controllers: 'static + RouterChain + Unpin + Send + Sync> Builder<Controlle
 }
 
 struct RouterInner {
-    resolvers: Vec<EndpointResolver>,
+    routes: RouteTree,
     chain: Box<dyn RouterChain + Send + Unpin + Sync>,
 }
 
@@ -198,20 +233,7 @@ impl Router {
     }
 
     pub fn resolve(&self, req: &mut Request<Body>) -> Result<u64, u16> {
-        let mut method_not_allowed = false;
-        for endpoint_resolver in &self.inner.resolvers {
-            match endpoint_resolver.resolve(req) {
-                EndpointResolverResult::InvalidPath => continue,
-                EndpointResolverResult::MethodNotAllowed => method_not_allowed = true,
-                EndpointResolverResult::Match => return Ok(endpoint_resolver.id()),
-            }
-        }
-
-        if method_not_allowed {
-            Err(405)
-        } else {
-            Err(404)
-        }
+        self.inner.routes.resolve(req)
     }
 
     pub async fn handle(self, mut req: Request<Body>) -> Result<Response<Body>, SaphirError> {
@@ -223,10 +245,20 @@ impl Router {
 
     pub async fn dispatch(&self, resolver_id: u64, req: Request<Body>) -> Result<Response<Body>, SaphirError> {
         // # SAFETY #
-        // The router is initialized in static memory when calling run on Server.
+        // The router is owned by the server stack, which is leaked and never freed.
         let static_self = unsafe { std::mem::transmute::<&'_ Self, &'static Self>(self) };
+        let disconnect = req.disconnect_token().clone();
+        let uri = req.uri().clone();
         if let Some(responder) = static_self.inner.chain.dispatch(resolver_id, req) {
-            responder.await.dyn_respond()
+            // The handler is cancelled once the client disconnects, the middlewares still get a
+            // response to report
+            match future::select(responder, disconnect.disconnected().boxed()).await {
+                Either::Left((mut responder, _)) => responder.dyn_respond(),
+                Either::Right(_) => {
+                    info!("client disconnected before request on {} was handled", uri);
+                    CLIENT_CLOSED_REQUEST_STATUS.respond()
+                }
+            }
         } else {
             404.respond()
         }
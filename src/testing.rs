@@ -0,0 +1,609 @@
+//! In-process client driving a server stack without sockets.
+//!
+//! A [`TestServer`](struct.TestServer.html) is built from the same `Server::builder()` as the real
+//! server, requests sent through its [`TestClient`](struct.TestClient.html) go through the
+//! middlewares, guards and router in memory. Any number of test servers can live in the same
+//! process.
+//!
+//! Guards and middlewares can also be unit tested on their own, with a request built by a
+//! [`RequestBuilder`](struct.RequestBuilder.html) and a [`StubChain`](struct.StubChain.html)
+//! standing for the rest of the middleware chain.
+//!
+//! ```rust
+//!# use saphir::prelude::*;
+//! use saphir::testing::TestServer;
+//!
+//! async fn hello(req: Request<Body>) -> (u16, String) {
+//!     let name = req.headers().get("x-name").and_then(|n| n.to_str().ok()).unwrap_or("world");
+//!     (200, format!("hello {}", name))
+//! }
+//!
+//!# #[tokio::main]
+//!# async fn main() -> Result<(), SaphirError> {
+//! let server = TestServer::new(Server::builder()
+//!     .configure_router(|r| r.route("/hello", Method::GET, hello))
+//!     .build());
+//!
+//! let client = server.client();
+//! client.get("/hello").header("x-name", "saphir").send().await?
+//!     .assert_status(200)
+//!     .assert_body("hello saphir");
+//! client.get("/missing").send().await?.assert_status(404);
+//!# Ok(())
+//!# }
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use cookie::Cookie;
+use futures::future::{self, BoxFuture, FutureExt};
+use http::header::{HeaderName, HeaderValue, COOKIE, SET_COOKIE};
+use http::request::Builder as RawRequestBuilder;
+use http::{HeaderMap, Method, Request as RawRequest, StatusCode, Uri};
+use hyper::body::Bytes;
+use hyper::Body;
+
+use crate::error::SaphirError;
+use crate::http_context::HttpContext;
+use crate::middleware::MiddlewareChain;
+use crate::request::{PeerAddr, Request};
+use crate::response::Response;
+use crate::router::Builder as RouterBuilder;
+use crate::server::{Server, Stack};
+
+/// Server stack driven in memory, see the [module documentation](index.html)
+///
+/// The listeners, lifecycle hooks and background tasks of the server are not used, nor are the
+/// connection level settings such as timeouts and limits. Only the request timeout of the first
+/// listener is enforced, or the default one if the server has no listener.
+pub struct TestServer {
+    stack: &'static Stack,
+    request_timeout_ms: Option<u64>,
+    request_timeout_status: u16,
+}
+
+impl TestServer {
+    /// Build a test server from the router and middlewares of `server`
+    pub fn new(server: Server) -> Self {
+        let (stack, request_timeout_ms, request_timeout_status) = server.into_stack();
+        TestServer {
+            // Like a running server, the stack is leaked so that the data borrowed by the
+            // controllers, guards and middlewares is never freed
+            stack: Box::leak(Box::new(stack)),
+            request_timeout_ms,
+            request_timeout_status,
+        }
+    }
+
+    /// Return a new client sending its requests to this server
+    #[inline]
+    pub fn client(&self) -> TestClient {
+        TestClient {
+            stack: self.stack,
+            request_timeout_ms: self.request_timeout_ms,
+            request_timeout_status: self.request_timeout_status,
+            peer_addr: None,
+            cookies: Vec::new(),
+        }
+    }
+
+    /// Send a request through the middlewares, guards and router of the server, returning the
+    /// response as the server would have sent it
+    pub async fn send(&self, req: Request<Body>) -> Result<Response<Body>, SaphirError> {
+        self.stack.invoke_with_timeout(req, self.request_timeout_ms, self.request_timeout_status).await
+    }
+}
+
+/// Client sending requests to a [`TestServer`](struct.TestServer.html)
+///
+/// The peer address and cookies set on the client are applied to every request it sends.
+///
+/// ```rust
+///# use saphir::prelude::*;
+/// use saphir::testing::TestServer;
+///
+/// async fn session(mut req: Request<Body>) -> (u16, String) {
+///     req.parse_cookies();
+///     let session = req.cookies().get("session").map(|c| c.value().to_string()).unwrap_or_default();
+///     (200, format!("{} from {}", session, req.peer_addr().unwrap()))
+/// }
+///
+///# #[tokio::main]
+///# async fn main() -> Result<(), SaphirError> {
+/// let server = TestServer::new(Server::builder()
+///     .configure_router(|r| r.route("/session", Method::GET, session))
+///     .build());
+///
+/// let client = server.client()
+///     .peer_addr("10.0.0.1:4000".parse::<std::net::SocketAddr>().unwrap())
+///     .cookie(Cookie::new("session", "abc"));
+/// client.get("/session").send().await?.assert_body("abc from 10.0.0.1:4000");
+///# Ok(())
+///# }
+/// ```
+#[derive(Clone)]
+pub struct TestClient {
+    stack: &'static Stack,
+    request_timeout_ms: Option<u64>,
+    request_timeout_status: u16,
+    peer_addr: Option<PeerAddr>,
+    cookies: Vec<Cookie<'static>>,
+}
+
+impl TestClient {
+    /// Set the peer address of the requests sent by this client. No peer address is set by
+    /// default.
+    #[inline]
+    pub fn peer_addr<A: Into<PeerAddr>>(mut self, peer_addr: A) -> Self {
+        self.peer_addr = Some(peer_addr.into());
+        self
+    }
+
+    /// Add a cookie to the requests sent by this client
+    #[inline]
+    pub fn cookie(mut self, cookie: Cookie<'static>) -> Self {
+        self.cookies.push(cookie);
+        self
+    }
+
+    /// Start building a request with the given method and uri
+    pub fn request<U>(&self, method: Method, uri: U) -> TestRequest
+        where Uri: TryFrom<U>,
+              <Uri as TryFrom<U>>::Error: Into<http::Error>
+    {
+        TestRequest {
+            client: self.clone(),
+            builder: RawRequest::builder().method(method).uri(uri),
+            cookies: Vec::new(),
+            body: Ok(Body::empty()),
+        }
+    }
+
+    /// Start building a `GET` request
+    #[inline]
+    pub fn get<U>(&self, uri: U) -> TestRequest
+        where Uri: TryFrom<U>,
+              <Uri as TryFrom<U>>::Error: Into<http::Error>
+    {
+        self.request(Method::GET, uri)
+    }
+
+    /// Start building a `POST` request
+    #[inline]
+    pub fn post<U>(&self, uri: U) -> TestRequest
+        where Uri: TryFrom<U>,
+              <Uri as TryFrom<U>>::Error: Into<http::Error>
+    {
+        self.request(Method::POST, uri)
+    }
+
+    /// Start building a `PUT` request
+    #[inline]
+    pub fn put<U>(&self, uri: U) -> TestRequest
+        where Uri: TryFrom<U>,
+              <Uri as TryFrom<U>>::Error: Into<http::Error>
+    {
+        self.request(Method::PUT, uri)
+    }
+
+    /// Start building a `PATCH` request
+    #[inline]
+    pub fn patch<U>(&self, uri: U) -> TestRequest
+        where Uri: TryFrom<U>,
+              <Uri as TryFrom<U>>::Error: Into<http::Error>
+    {
+        self.request(Method::PATCH, uri)
+    }
+
+    /// Start building a `DELETE` request
+    #[inline]
+    pub fn delete<U>(&self, uri: U) -> TestRequest
+        where Uri: TryFrom<U>,
+              <Uri as TryFrom<U>>::Error: Into<http::Error>
+    {
+        self.request(Method::DELETE, uri)
+    }
+
+    /// Send a raw request with the peer address and cookies of this client
+    pub async fn send(&self, req: RawRequest<Body>) -> Result<TestResponse, SaphirError> {
+        let mut req = req;
+        if !self.cookies.is_empty() {
+            let cookies = self.cookies.iter().map(|c| format!("{}={}", c.name(), c.value())).collect::<Vec<_>>().join("; ");
+            req.headers_mut().append(COOKIE, HeaderValue::from_str(&cookies)?);
+        }
+
+        let req = Request::new(req, self.peer_addr.clone());
+        let res = self.stack.invoke_with_timeout(req, self.request_timeout_ms, self.request_timeout_status).await?.into_raw()?;
+        let (parts, body) = res.into_parts();
+        let body = hyper::body::to_bytes(body).await.map_err(|e| SaphirError::Custom(Box::new(e)))?;
+
+        Ok(TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        })
+    }
+}
+
+/// Request being built by a [`TestClient`](struct.TestClient.html)
+pub struct TestRequest {
+    client: TestClient,
+    builder: RawRequestBuilder,
+    cookies: Vec<Cookie<'static>>,
+    body: Result<Body, SaphirError>,
+}
+
+impl TestRequest {
+    /// Append a header to the request
+    #[inline]
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+        where HeaderName: TryFrom<K>,
+              <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+              HeaderValue: TryFrom<V>,
+              <HeaderValue as TryFrom<V>>::Error: Into<http::Error>
+    {
+        self.builder = self.builder.header(key, value);
+        self
+    }
+
+    /// Add a cookie to the request, along the cookies of the client
+    #[inline]
+    pub fn cookie(mut self, cookie: Cookie<'static>) -> Self {
+        self.cookies.push(cookie);
+        self
+    }
+
+    /// Set the peer address of the request, overriding the one of the client
+    #[inline]
+    pub fn peer_addr<A: Into<PeerAddr>>(mut self, peer_addr: A) -> Self {
+        self.client.peer_addr = Some(peer_addr.into());
+        self
+    }
+
+    /// Set the body of the request
+    #[inline]
+    pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
+        self.body = Ok(body.into());
+        self
+    }
+
+    /// Using Feature `json`
+    ///
+    /// Set the body of the request to `value` serialized as json, along a `content-type` header
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(mut self, value: &T) -> Self {
+        self.body = serde_json::to_vec(value).map(Body::from).map_err(|e| SaphirError::Custom(Box::new(e)));
+        self.header(http::header::CONTENT_TYPE, "application/json")
+    }
+
+    /// Send the request to the server
+    pub async fn send(self) -> Result<TestResponse, SaphirError> {
+        let TestRequest { mut client, builder, cookies, body } = self;
+        client.cookies.extend(cookies);
+        let req = builder.body(body?)?;
+        client.send(req).await
+    }
+}
+
+/// Response received by a [`TestClient`](struct.TestClient.html), with its body fully read
+///
+/// The `assert_*` methods panic with the received value when it is not the expected one, and can
+/// be chained.
+#[derive(Debug)]
+pub struct TestResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestResponse {
+    /// Return the status of the response
+    #[inline]
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Return the headers of the response, including a `set-cookie` header for every cookie added
+    /// to the response
+    #[inline]
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Return the value of the header named `name`, if it is present and valid utf-8
+    #[inline]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    /// Return the cookie named `name` set by the response
+    pub fn cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        self.headers.get_all(SET_COOKIE).iter()
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(|v| Cookie::parse(v.to_string()).ok())
+            .find(|c| c.name() == name)
+    }
+
+    /// Return the body of the response
+    #[inline]
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Return the body of the response as text, invalid utf-8 sequences are replaced
+    #[inline]
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Using Feature `json`
+    ///
+    /// Deserialize the body of the response from json
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, SaphirError> {
+        serde_json::from_slice(&self.body).map_err(|e| SaphirError::Custom(Box::new(e)))
+    }
+
+    /// Assert that the status of the response is `status`
+    #[track_caller]
+    pub fn assert_status(&self, status: u16) -> &Self {
+        assert_eq!(self.status.as_u16(), status, "unexpected response status, body: {}", self.text());
+        self
+    }
+
+    /// Assert that the response has a header named `name` with the value `value`
+    #[track_caller]
+    pub fn assert_header(&self, name: &str, value: &str) -> &Self {
+        assert_eq!(self.header(name), Some(value), "unexpected value for response header {}", name);
+        self
+    }
+
+    /// Assert that the response has no header named `name`
+    #[track_caller]
+    pub fn assert_no_header(&self, name: &str) -> &Self {
+        assert_eq!(self.header(name), None, "unexpected response header {}", name);
+        self
+    }
+
+    /// Assert that the body of the response is `body`
+    #[track_caller]
+    pub fn assert_body<B: AsRef<[u8]> + Debug>(&self, body: B) -> &Self {
+        assert_eq!(self.body.as_ref(), body.as_ref(), "unexpected response body {:?}, expected {:?}", self.text(), body);
+        self
+    }
+
+    /// Using Feature `json`
+    ///
+    /// Assert that the body of the response is the json representation of `value`
+    #[cfg(feature = "json")]
+    #[track_caller]
+    pub fn assert_json<T>(&self, value: &T) -> &Self
+        where T: serde::de::DeserializeOwned + PartialEq + Debug
+    {
+        match self.json::<T>() {
+            Ok(body) => assert_eq!(&body, value, "unexpected json response body"),
+            Err(e) => panic!("response body is not valid json: {}, body: {}", e, self.text()),
+        }
+        self
+    }
+}
+
+/// Builder of a [`Request`](../request/struct.Request.html) or an
+/// [`HttpContext`](../http_context/struct.HttpContext.html) as the router and middlewares would
+/// hand it over, to unit test a guard or a middleware on its own
+///
+/// ```rust
+///# use saphir::prelude::*;
+/// use saphir::testing::RequestBuilder;
+///
+/// async fn forbidder_guard(forbidden: &String, req: Request<Body>) -> Result<Request<Body>, u16> {
+///     if req.captures().get("variable") == Some(forbidden) {
+///         Err(403)
+///     } else {
+///         Ok(req)
+///     }
+/// }
+///
+///# #[tokio::main]
+///# async fn main() -> Result<(), SaphirError> {
+/// let forbidden = "password".to_string();
+/// let req = RequestBuilder::new(Method::GET, "/password/print").capture("variable", "password").build()?;
+/// assert_eq!(forbidder_guard(&forbidden, req).await.err(), Some(403));
+///
+/// let req = RequestBuilder::new(Method::GET, "/hello/print").capture("variable", "hello").build()?;
+/// assert!(forbidder_guard(&forbidden, req).await.is_ok());
+///# Ok(())
+///# }
+/// ```
+pub struct RequestBuilder {
+    builder: RawRequestBuilder,
+    body: Body,
+    captures: Vec<(String, String)>,
+    cookies: Vec<Cookie<'static>>,
+    peer_addr: Option<PeerAddr>,
+}
+
+impl RequestBuilder {
+    /// Start building a request with the given method and uri
+    pub fn new<U>(method: Method, uri: U) -> Self
+        where Uri: TryFrom<U>,
+              <Uri as TryFrom<U>>::Error: Into<http::Error>
+    {
+        RequestBuilder {
+            builder: RawRequest::builder().method(method).uri(uri),
+            body: Body::empty(),
+            captures: Vec::new(),
+            cookies: Vec::new(),
+            peer_addr: None,
+        }
+    }
+
+    /// Append a header to the request
+    #[inline]
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+        where HeaderName: TryFrom<K>,
+              <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+              HeaderValue: TryFrom<V>,
+              <HeaderValue as TryFrom<V>>::Error: Into<http::Error>
+    {
+        self.builder = self.builder.header(key, value);
+        self
+    }
+
+    /// Insert an extension into the request
+    #[inline]
+    pub fn extension<T: Send + Sync + 'static>(mut self, extension: T) -> Self {
+        self.builder = self.builder.extension(extension);
+        self
+    }
+
+    /// Preset a path capture, as if the router matched a route with a variable named `name`
+    #[inline]
+    pub fn capture<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.captures.push((name.into(), value.into()));
+        self
+    }
+
+    /// Add a cookie to the request. The cookie is sent in the `cookie` header and is already
+    /// available through [`cookies`](../request/struct.Request.html#method.cookies).
+    #[inline]
+    pub fn cookie(mut self, cookie: Cookie<'static>) -> Self {
+        self.cookies.push(cookie);
+        self
+    }
+
+    /// Set the peer address of the request
+    #[inline]
+    pub fn peer_addr<A: Into<PeerAddr>>(mut self, peer_addr: A) -> Self {
+        self.peer_addr = Some(peer_addr.into());
+        self
+    }
+
+    /// Set the body of the request
+    #[inline]
+    pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Finish the builder into a request
+    pub fn build(self) -> Result<Request<Body>, SaphirError> {
+        let RequestBuilder { mut builder, body, captures, cookies, peer_addr } = self;
+        if !cookies.is_empty() {
+            let header = cookies.iter().map(|c| format!("{}={}", c.name(), c.value())).collect::<Vec<_>>().join("; ");
+            builder = builder.header(COOKIE, header);
+        }
+
+        let mut req = Request::new(builder.body(body)?, peer_addr);
+        req.captures_mut().extend(captures);
+        cookies.into_iter().for_each(|c| req.cookies_mut().add_original(c));
+
+        Ok(req)
+    }
+
+    /// Finish the builder into the context handed over to a middleware. Calling the router of the
+    /// context, which is what the end of a real middleware chain does, answers with a 404.
+    ///
+    /// ```rust
+    ///# use saphir::prelude::*;
+    /// use saphir::testing::{RequestBuilder, StubChain};
+    ///
+    /// async fn api_key_middleware(key: &String, ctx: HttpContext<Body>, chain: &dyn MiddlewareChain) -> Result<Response<Body>, SaphirError> {
+    ///     match ctx.request.headers().get("x-api-key") {
+    ///         Some(value) if value == key.as_str() => chain.next(ctx).await,
+    ///         _ => 401.respond(),
+    ///     }
+    /// }
+    ///
+    ///# #[tokio::main]
+    ///# async fn main() -> Result<(), SaphirError> {
+    /// let key = "secret".to_string();
+    /// let chain = StubChain::new(200);
+    ///
+    /// let ctx = RequestBuilder::new(Method::GET, "/").build_context()?;
+    /// assert_eq!(api_key_middleware(&key, ctx, &chain).await?.status(), 401);
+    /// assert_eq!(chain.calls(), 0);
+    ///
+    /// let ctx = RequestBuilder::new(Method::GET, "/").header("x-api-key", "secret").build_context()?;
+    /// assert_eq!(api_key_middleware(&key, ctx, &chain).await?.status(), 200);
+    /// assert_eq!(chain.calls(), 1);
+    ///# Ok(())
+    ///# }
+    /// ```
+    pub fn build_context(self) -> Result<HttpContext<Body>, SaphirError> {
+        Ok(HttpContext::new(self.build()?, RouterBuilder::default().build()))
+    }
+}
+
+/// Middleware chain answering every request with a canned response, standing for the rest of the
+/// chain when unit testing a middleware. See
+/// [`RequestBuilder::build_context`](struct.RequestBuilder.html#method.build_context).
+pub struct StubChain {
+    status: u16,
+    headers: HeaderMap,
+    body: Bytes,
+    calls: AtomicUsize,
+    last_request: Mutex<Option<Request<Body>>>,
+}
+
+impl StubChain {
+    /// Create a chain answering with an empty response of the given status
+    pub fn new(status: u16) -> Self {
+        StubChain {
+            status,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            calls: AtomicUsize::new(0),
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Append a header to the canned response
+    #[inline]
+    pub fn header<K, V>(mut self, key: K, value: V) -> Result<Self, SaphirError>
+        where HeaderName: TryFrom<K>,
+              <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+              HeaderValue: TryFrom<V>,
+              <HeaderValue as TryFrom<V>>::Error: Into<http::Error>
+    {
+        let key = HeaderName::try_from(key).map_err(Into::into)?;
+        let value = HeaderValue::try_from(value).map_err(Into::into)?;
+        self.headers.append(key, value);
+        Ok(self)
+    }
+
+    /// Set the body of the canned response
+    #[inline]
+    pub fn body<B: Into<Bytes>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Return the number of times the chain was called
+    #[inline]
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Take the last request which reached the chain, as modified by the middleware under test
+    pub fn take_request(&self) -> Option<Request<Body>> {
+        self.last_request.lock().unwrap_or_else(PoisonError::into_inner).take()
+    }
+}
+
+impl MiddlewareChain for StubChain {
+    fn next(&self, ctx: HttpContext<Body>) -> BoxFuture<'static, Result<Response<Body>, SaphirError>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        *self.last_request.lock().unwrap_or_else(PoisonError::into_inner) = Some(ctx.request);
+
+        let mut builder = crate::response::Builder::new().status(self.status).body(self.body.clone());
+        if let Some(headers) = builder.headers_mut() {
+            headers.extend(self.headers.clone());
+        }
+
+        future::ready(builder.build()).boxed()
+    }
+}
@@ -4,7 +4,7 @@ use http::Method;
 use crate::request::Request;
 use hyper::Body;
 use crate::error::SaphirError;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::atomic::AtomicU64;
 
 static ENDPOINT_ID: AtomicU64 = AtomicU64::new(0);
@@ -38,6 +38,10 @@ impl EndpointResolver {
         self.methods.insert(m);
     }
 
+    pub fn allows(&self, m: &Method) -> bool {
+        self.methods.contains(m)
+    }
+
     pub fn resolve(&self, req: &mut Request<Body>) -> EndpointResolverResult {
         if req.current_path_match_all(&self.path_matcher) {
             if self.methods.contains(req.method()) {
@@ -55,6 +59,109 @@ impl EndpointResolver {
     }
 }
 
+/// Prefix tree of the endpoints, matching a request path one segment at a time.
+///
+/// At every segment, static segments are tried before regex ones and regex ones before variables.
+/// If no endpoint matches past a segment, the next candidate is tried. Endpoints sharing the same
+/// path, or regex segments sharing a node, are tried in the order they were added to the router.
+///
+/// Every node is visited at most once per request, and only if one of the endpoints below it has
+/// as many segments as the request path has left.
+#[derive(Default)]
+pub(crate) struct RouteTree {
+    root: RouteNode,
+}
+
+impl RouteTree {
+    pub fn new<I: IntoIterator<Item = EndpointResolver>>(resolvers: I) -> Self {
+        let mut resolvers: Vec<EndpointResolver> = resolvers.into_iter().collect();
+        // Endpoint ids are handed out as routes are added
+        resolvers.sort_by_key(EndpointResolver::id);
+
+        let mut tree = RouteTree::default();
+        for resolver in resolvers {
+            tree.insert(resolver);
+        }
+
+        tree
+    }
+
+    fn insert(&mut self, resolver: EndpointResolver) {
+        let segments = resolver.path_matcher.len();
+        let mut node = &mut self.root;
+        node.remaining_segments.insert(segments);
+        for (depth, segment) in resolver.path_matcher.iter().enumerate() {
+            node = match segment {
+                UriPathSegmentMatcher::Static { segment: ref s } => node.statics.entry(s.clone()).or_default(),
+                UriPathSegmentMatcher::Variable { name: ref _n } => node.variable.get_or_insert_with(Default::default),
+                UriPathSegmentMatcher::Custom { name: ref _n, segment: ref r } => {
+                    let index = match node.customs.iter().position(|(custom, _)| custom.as_str() == r.as_str()) {
+                        Some(index) => index,
+                        None => {
+                            node.customs.push((r.clone(), RouteNode::default()));
+                            node.customs.len() - 1
+                        }
+                    };
+                    &mut node.customs[index].1
+                }
+            };
+            node.remaining_segments.insert(segments - depth - 1);
+        }
+
+        node.endpoints.push(resolver);
+    }
+
+    /// Resolve the endpoint of the request, capturing its path variables. Fails with 405 if the
+    /// path only matches endpoints not allowing the method of the request, with 404 otherwise.
+    pub fn resolve(&self, req: &mut Request<Body>) -> Result<u64, u16> {
+        let mut method_not_allowed = false;
+        match self.root.find(req.current_path(), 0, req.method(), &mut method_not_allowed) {
+            Some(endpoint) => {
+                // The tree only returns an endpoint whose every segment matched the request path
+                req.capture_path(&endpoint.path_matcher);
+                Ok(endpoint.id())
+            }
+            None if method_not_allowed => Err(405),
+            None => Err(404),
+        }
+    }
+}
+
+#[derive(Default)]
+struct RouteNode {
+    endpoints: Vec<EndpointResolver>,
+    /// Number of segments left to each endpoint of this node or below it
+    remaining_segments: HashSet<usize>,
+    statics: HashMap<String, RouteNode>,
+    customs: Vec<(Regex, RouteNode)>,
+    variable: Option<Box<RouteNode>>,
+}
+
+impl RouteNode {
+    /// Find the first endpoint matching the segments of `path` from `depth` on and allowing `method`
+    fn find(&self, path: &VecDeque<String>, depth: usize, method: &Method, method_not_allowed: &mut bool) -> Option<&EndpointResolver> {
+        if !self.remaining_segments.contains(&(path.len() - depth)) {
+            return None;
+        }
+
+        let segment = match path.get(depth) {
+            Some(segment) => segment,
+            None => {
+                let endpoint = self.endpoints.iter().find(|e| e.allows(method));
+                *method_not_allowed |= endpoint.is_none() && !self.endpoints.is_empty();
+                return endpoint;
+            }
+        };
+
+        let statics = self.statics.get(segment);
+        let customs = self.customs.iter().filter(|(r, _)| r.is_match(segment)).map(|(_, node)| node);
+        statics.into_iter()
+            .chain(customs)
+            .chain(self.variable.as_deref())
+            .find_map(|node| node.find(path, depth + 1, method, method_not_allowed))
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct UriPathMatcher {
     inner: Vec<UriPathSegmentMatcher>
@@ -175,4 +282,70 @@ impl UriPathSegmentMatcher {
             UriPathSegmentMatcher::Custom { name: ref n, segment: ref _s } => n.as_ref().map(|s| s.as_str()),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(routes: &[(&str, Method)]) -> (RouteTree, Vec<u64>) {
+        let resolvers = routes.iter().map(|(path, method)| EndpointResolver::new(path, method.clone()).unwrap()).collect::<Vec<_>>();
+        let ids = resolvers.iter().map(EndpointResolver::id).collect();
+        (RouteTree::new(resolvers), ids)
+    }
+
+    fn request(method: Method, path: &str) -> Request<Body> {
+        Request::new(hyper::Request::builder().method(method).uri(path).body(Body::empty()).unwrap(), None)
+    }
+
+    #[test]
+    fn resolve_captures_variables() {
+        let (tree, ids) = tree(&[("/users/{id#r(^[0-9]+$)}/posts/{post}", Method::GET)]);
+        let mut req = request(Method::GET, "/users/42/posts/hello");
+
+        assert_eq!(tree.resolve(&mut req), Ok(ids[0]));
+        assert_eq!(req.captures().get("id").map(String::as_str), Some("42"));
+        assert_eq!(req.captures().get("post").map(String::as_str), Some("hello"));
+        assert!(req.current_path().is_empty());
+    }
+
+    #[test]
+    fn resolve_backtracks_to_the_next_candidate() {
+        let (tree, ids) = tree(&[
+            ("/users/me", Method::GET),
+            ("/users/{id#r(^[0-9]+$)}/posts", Method::GET),
+            ("/users/{name}/profile", Method::GET),
+        ]);
+
+        assert_eq!(tree.resolve(&mut request(Method::GET, "/users/me")), Ok(ids[0]));
+        assert_eq!(tree.resolve(&mut request(Method::GET, "/users/42/posts")), Ok(ids[1]));
+        assert_eq!(tree.resolve(&mut request(Method::GET, "/users/me/profile")), Ok(ids[2]));
+        assert_eq!(tree.resolve(&mut request(Method::GET, "/users/42/profile")), Ok(ids[2]));
+
+        let mut req = request(Method::GET, "/users/me/profile");
+        tree.resolve(&mut req).unwrap();
+        assert_eq!(req.captures().get("name").map(String::as_str), Some("me"));
+    }
+
+    #[test]
+    fn resolve_not_found_or_not_allowed() {
+        let (tree, _) = tree(&[("/users/{id}", Method::GET), ("/users/{id}/posts", Method::POST)]);
+
+        assert_eq!(tree.resolve(&mut request(Method::GET, "/users")), Err(404));
+        assert_eq!(tree.resolve(&mut request(Method::GET, "/users/42/posts/1")), Err(404));
+        assert_eq!(tree.resolve(&mut request(Method::GET, "/users/42/posts")), Err(405));
+        assert_eq!(tree.resolve(&mut request(Method::DELETE, "/users/42")), Err(405));
+    }
+
+    #[test]
+    fn nodes_know_the_segments_left_below_them() {
+        let (tree, _) = tree(&[("/a/{b}/c", Method::GET), ("/a/{b}", Method::GET), ("/d", Method::GET)]);
+
+        let expected = |lengths: &[usize]| lengths.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(tree.root.remaining_segments, expected(&[1, 2, 3]));
+        let a = &tree.root.statics["a"];
+        assert_eq!(a.remaining_segments, expected(&[1, 2]));
+        assert_eq!(a.variable.as_ref().unwrap().remaining_segments, expected(&[0, 1]));
+        assert_eq!(tree.root.statics["d"].remaining_segments, expected(&[0]));
+    }
+}